@@ -4,5 +4,5 @@ extern crate ndarray;
 mod model;
 mod solver;
 
-pub use model::Puzzle;
-pub use solver::Solver;
+pub use model::{ColorId, Puzzle, PuzzleParseError, Grid, Square};
+pub use solver::{Solver, SolutionCount, SolverError};
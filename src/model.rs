@@ -1,21 +1,28 @@
 use std::fmt;
+use std::io::BufRead;
+use std::str::FromStr;
 
 use ndarray::{
     Array2,
+    ArrayView1,
     ArrayViewMut1,
     Axis,
 };
 
 
+// Palette index for a colored nonogram. Monochrome puzzles just use color 0.
+pub type ColorId = u8;
+
+#[derive(Debug)]
 pub struct Puzzle {
-    row_hints: Vec<Vec<usize>>,
-    col_hints: Vec<Vec<usize>>,
+    row_hints: Vec<Vec<(usize, ColorId)>>,
+    col_hints: Vec<Vec<(usize, ColorId)>>,
 }
 
-#[derive(Clone, Copy, PartialEq)]
+#[derive(Clone, Copy, PartialEq, Debug)]
 pub enum Square {
     Empty,
-    Full,
+    Full(ColorId),
 }
 
 #[derive(Clone, Copy, Hash, PartialEq, Eq, Debug)]
@@ -24,6 +31,7 @@ pub enum LineIndex {
     Col(usize),
 }
 
+#[derive(Clone, PartialEq, Debug)]
 pub struct Grid<T>(pub Array2<T>);
 
 impl<T> Grid<T> {
@@ -40,6 +48,13 @@ impl<T> Grid<T> {
             LineIndex::Col(j) => self.0.slice_mut(s![.., j]),
         }
     }
+
+    pub fn line_view<'a>(&'a self, li: LineIndex) -> ArrayView1<'a, T> {
+        match li {
+            LineIndex::Row(i) => self.0.slice(s![i, ..]),
+            LineIndex::Col(j) => self.0.slice(s![.., j]),
+        }
+    }
 }
 
 impl LineIndex {
@@ -87,8 +102,9 @@ impl<'a> Iterator for LineIndexIterator {
 impl fmt::Display for Square {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            Square::Empty => write!(f, "x"),
-            Square::Full  => write!(f, "█"),
+            Square::Empty    => write!(f, "x"),
+            Square::Full(0)  => write!(f, "█"),
+            Square::Full(c)  => write!(f, "{}", (b'A' + c % 26) as char),
         }
     }
 }
@@ -164,23 +180,34 @@ impl Puzzle {
         self.row_hints.len()
     }
 
-    pub fn push_row(mut self, hints: Vec<usize>) -> Self {
+    pub fn push_row(mut self, hints: Vec<(usize, ColorId)>) -> Self {
         self.row_hints.push(hints);
         self
     }
 
-    pub fn push_col(mut self, hints: Vec<usize>) -> Self {
+    pub fn push_col(mut self, hints: Vec<(usize, ColorId)>) -> Self {
         self.col_hints.push(hints);
         self
     }
 
-    pub fn hints(&self, li: LineIndex) -> &[usize] {
+    pub fn hints(&self, li: LineIndex) -> &[(usize, ColorId)] {
         match li {
             LineIndex::Row(i) => &self.row_hints[i],
             LineIndex::Col(j) => &self.col_hints[j],
         }
     }
 
+    // Size of the color palette actually used by this puzzle's hints, so the
+    // solver knows how many candidate colors a cell can start out with.
+    pub fn num_colors(&self) -> ColorId {
+        self.row_hints.iter()
+            .chain(self.col_hints.iter())
+            .flat_map(|hints| hints.iter())
+            .map(|&(_, color)| color)
+            .max()
+            .map_or(1, |max_color| max_color + 1)
+    }
+
     pub fn index_iter(&self) -> LineIndexIterator {
         // FIXME: Require that Row(0) is valid (>= 1 rows)
         LineIndexIterator {
@@ -189,6 +216,167 @@ impl Puzzle {
             li: Some(LineIndex::Row(0)),
         }
     }
+
+    // Text format: a "w h" header, then `h` lines of row hints, then `w`
+    // lines of column hints, one line per line-of-the-puzzle. Each hint is
+    // space-separated `len` (color 0) or `len:color`; a blank line means no
+    // hints (an all-empty line).
+    pub fn from_reader<R: BufRead>(reader: R) -> Result<Self, PuzzleParseError> {
+        let mut lines = reader.lines();
+
+        let header = lines.next()
+            .ok_or(PuzzleParseError::MissingHeader)?
+            .map_err(|e| PuzzleParseError::Io(e.to_string()))?;
+
+        let mut fields = header.split_whitespace();
+        let w = fields.next()
+            .and_then(|s| s.parse::<usize>().ok())
+            .ok_or_else(|| PuzzleParseError::BadHeader(header.clone()))?;
+        let h = fields.next()
+            .and_then(|s| s.parse::<usize>().ok())
+            .ok_or_else(|| PuzzleParseError::BadHeader(header.clone()))?;
+
+        let mut puzzle = Puzzle::with_capacity(w, h);
+
+        for _ in 0..h {
+            let line = lines.next()
+                .ok_or(PuzzleParseError::WrongLineCount { side: "row", expected: h, found: puzzle.h() })?
+                .map_err(|e| PuzzleParseError::Io(e.to_string()))?;
+            puzzle = puzzle.push_row(parse_hint_line(&line)?);
+        }
+
+        for _ in 0..w {
+            let line = lines.next()
+                .ok_or(PuzzleParseError::WrongLineCount { side: "col", expected: w, found: puzzle.w() })?
+                .map_err(|e| PuzzleParseError::Io(e.to_string()))?;
+            puzzle = puzzle.push_col(parse_hint_line(&line)?);
+        }
+
+        Ok(puzzle)
+    }
+
+    // The reverse of solving: derive a puzzle's row/col hints from a fully
+    // drawn grid by run-length-encoding its `Full` segments, so a puzzle can
+    // be authored as a bitmap instead of hand-written hints.
+    pub fn from_grid(grid: &Grid<Square>) -> Self {
+        let mut puzzle = Puzzle::with_capacity(grid.w(), grid.h());
+
+        for i in 0..grid.h() {
+            puzzle = puzzle.push_row(line_hints(grid.line_view(LineIndex::Row(i))));
+        }
+
+        for j in 0..grid.w() {
+            puzzle = puzzle.push_col(line_hints(grid.line_view(LineIndex::Col(j))));
+        }
+
+        puzzle
+    }
+}
+
+fn line_hints(line: ArrayView1<Square>) -> Vec<(usize, ColorId)> {
+    let mut hints = Vec::new();
+    let mut current: Option<(usize, ColorId)> = None;
+
+    for square in line.iter() {
+        match *square {
+            Square::Full(color) => {
+                current = Some(match current {
+                    Some((len, c)) if c == color => (len + 1, c),
+                    Some((len, c)) => { hints.push((len, c)); (1, color) }
+                    None => (1, color),
+                });
+            }
+            Square::Empty => {
+                if let Some(run) = current.take() {
+                    hints.push(run);
+                }
+            }
+        }
+    }
+
+    if let Some(run) = current {
+        hints.push(run);
+    }
+
+    hints
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum PuzzleParseError {
+    Io(String),
+    MissingHeader,
+    BadHeader(String),
+    WrongLineCount { side: &'static str, expected: usize, found: usize },
+    BadHint(String),
+}
+
+impl fmt::Display for PuzzleParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PuzzleParseError::Io(msg) =>
+                write!(f, "i/o error reading puzzle: {}", msg),
+            PuzzleParseError::MissingHeader =>
+                write!(f, "missing \"width height\" header line"),
+            PuzzleParseError::BadHeader(line) =>
+                write!(f, "expected \"width height\" header, got {:?}", line),
+            PuzzleParseError::WrongLineCount { side, expected, found } =>
+                write!(f, "expected {} {} hint lines, found {}", expected, side, found),
+            PuzzleParseError::BadHint(tok) =>
+                write!(f, "invalid hint {:?}, expected \"len\" or \"len:color\"", tok),
+        }
+    }
+}
+
+impl std::error::Error for PuzzleParseError {}
+
+impl FromStr for Puzzle {
+    type Err = PuzzleParseError;
+
+    fn from_str(s: &str) -> Result<Self, PuzzleParseError> {
+        Puzzle::from_reader(s.as_bytes())
+    }
+}
+
+fn parse_hint(tok: &str) -> Result<(usize, ColorId), PuzzleParseError> {
+    let mut parts = tok.splitn(2, ':');
+    let len = parts.next().unwrap().parse::<usize>()
+        .map_err(|_| PuzzleParseError::BadHint(tok.to_owned()))?;
+    let color = match parts.next() {
+        Some(c) => c.parse::<ColorId>().map_err(|_| PuzzleParseError::BadHint(tok.to_owned()))?,
+        None => 0,
+    };
+    Ok((len, color))
+}
+
+fn parse_hint_line(line: &str) -> Result<Vec<(usize, ColorId)>, PuzzleParseError> {
+    line.split_whitespace().map(parse_hint).collect()
+}
+
+fn format_hint_line(hints: &[(usize, ColorId)]) -> String {
+    hints.iter()
+        .map(|&(len, color)| if color == 0 {
+            len.to_string()
+        } else {
+            format!("{}:{}", len, color)
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+impl fmt::Display for Puzzle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{} {}", self.w(), self.h())?;
+
+        for hints in self.row_hints.iter() {
+            writeln!(f, "{}", format_hint_line(hints))?;
+        }
+
+        for hints in self.col_hints.iter() {
+            writeln!(f, "{}", format_hint_line(hints))?;
+        }
+
+        Ok(())
+    }
 }
 
 
@@ -199,18 +387,119 @@ mod tests {
     #[test]
     fn new_puzzle_grid_has_correct_dimensions() {
         let puzzle = Puzzle::new()
-            .push_row(vec!(5))
-            .push_row(vec!(1))
-            .push_row(vec!(5))
-            .push_row(vec!(1))
-            .push_row(vec!(5))
-            .push_col(vec!(3, 1))
-            .push_col(vec!(1, 1, 1))
-            .push_col(vec!(1, 1, 1))
-            .push_col(vec!(1, 1, 1))
-            .push_col(vec!(1, 3));
+            .push_row(vec!((5, 0)))
+            .push_row(vec!((1, 0)))
+            .push_row(vec!((5, 0)))
+            .push_row(vec!((1, 0)))
+            .push_row(vec!((5, 0)))
+            .push_col(vec!((3, 0), (1, 0)))
+            .push_col(vec!((1, 0), (1, 0), (1, 0)))
+            .push_col(vec!((1, 0), (1, 0), (1, 0)))
+            .push_col(vec!((1, 0), (1, 0), (1, 0)))
+            .push_col(vec!((1, 0), (3, 0)));
 
         assert_eq!(puzzle.w(), 5);
         assert_eq!(puzzle.h(), 5);
     }
+
+    #[test]
+    fn num_colors_defaults_to_one_for_monochrome_hints() {
+        let puzzle = Puzzle::new()
+            .push_row(vec!((1, 0)))
+            .push_col(vec!((1, 0)));
+
+        assert_eq!(puzzle.num_colors(), 1);
+    }
+
+    #[test]
+    fn num_colors_counts_highest_color_seen() {
+        let puzzle = Puzzle::new()
+            .push_row(vec!((1, 0), (1, 2)))
+            .push_col(vec!((1, 0)))
+            .push_col(vec!((1, 2)));
+
+        assert_eq!(puzzle.num_colors(), 3);
+    }
+
+    #[test]
+    fn parses_monochrome_text_format() {
+        let text = "5 5\n5\n1\n5\n1\n5\n3 1\n1 1 1\n1 1 1\n1 1 1\n1 3\n";
+        let puzzle = Puzzle::from_reader(text.as_bytes()).unwrap();
+
+        assert_eq!(puzzle.w(), 5);
+        assert_eq!(puzzle.h(), 5);
+        assert_eq!(puzzle.hints(LineIndex::Row(0)), &[(5, 0)]);
+        assert_eq!(puzzle.hints(LineIndex::Col(0)), &[(3, 0), (1, 0)]);
+    }
+
+    #[test]
+    fn parses_colored_hints_and_blank_lines() {
+        let puzzle = "2 2\n1:2\n\n1:2\n\n".parse::<Puzzle>().unwrap();
+
+        assert_eq!(puzzle.hints(LineIndex::Row(0)), &[(1, 2)]);
+        assert_eq!(puzzle.hints(LineIndex::Row(1)), &[] as &[(usize, ColorId)]);
+        assert_eq!(puzzle.hints(LineIndex::Col(0)), &[(1, 2)]);
+    }
+
+    #[test]
+    fn display_and_parse_round_trip() {
+        let original = Puzzle::new()
+            .push_row(vec!((3, 0), (1, 2)))
+            .push_row(vec!((2, 1)))
+            .push_col(vec!((1, 0)))
+            .push_col(vec!((1, 0)))
+            .push_col(vec!((1, 2)));
+
+        let rendered = original.to_string();
+        let parsed = rendered.parse::<Puzzle>().unwrap();
+
+        assert_eq!(parsed.w(), original.w());
+        assert_eq!(parsed.h(), original.h());
+        assert_eq!(parsed.hints(LineIndex::Row(0)), original.hints(LineIndex::Row(0)));
+        assert_eq!(parsed.hints(LineIndex::Col(2)), original.hints(LineIndex::Col(2)));
+    }
+
+    #[test]
+    fn rejects_missing_header() {
+        let err = Puzzle::from_reader("".as_bytes()).unwrap_err();
+        assert_eq!(err, PuzzleParseError::MissingHeader);
+    }
+
+    #[test]
+    fn rejects_wrong_hint_line_count() {
+        let err = Puzzle::from_reader("2 2\n1\n".as_bytes()).unwrap_err();
+        assert_eq!(err, PuzzleParseError::WrongLineCount { side: "row", expected: 2, found: 1 });
+    }
+
+    #[test]
+    fn rejects_malformed_hint() {
+        let err = Puzzle::from_reader("1 1\nfoo\n1\n".as_bytes()).unwrap_err();
+        assert_eq!(err, PuzzleParseError::BadHint("foo".to_owned()));
+    }
+
+    #[test]
+    fn from_grid_derives_hints_from_solved_grid() {
+        let e = Square::Empty;
+        let f = Square::Full(0);
+        let cells = vec![
+            f, e, f,
+            f, f, f,
+        ];
+        let grid = Grid(Array2::from_shape_vec((2, 3), cells).unwrap());
+
+        let puzzle = Puzzle::from_grid(&grid);
+
+        assert_eq!(puzzle.w(), 3);
+        assert_eq!(puzzle.h(), 2);
+        assert_eq!(puzzle.hints(LineIndex::Row(0)), &[(1, 0), (1, 0)]);
+        assert_eq!(puzzle.hints(LineIndex::Row(1)), &[(3, 0)]);
+        assert_eq!(puzzle.hints(LineIndex::Col(0)), &[(2, 0)]);
+    }
+
+    #[test]
+    fn square_display_does_not_overflow_for_high_color_ids() {
+        // ColorId is a u8, so 200 is a valid palette index, but b'A' + 200
+        // overflows a u8 (max glyph room is ~190 colors past 'A').
+        assert_eq!(Square::Full(200).to_string(), "S");
+    }
 }
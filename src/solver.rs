@@ -1,10 +1,10 @@
 use std::collections::{HashMap, HashSet, VecDeque};
 use std::fmt;
-use std::cmp::{min, max};
 
-use ndarray::{ArrayViewMut1, Array2};
+use ndarray::{ArrayView1, ArrayViewMut1, Array2};
 
 use model::{
+    ColorId,
     Puzzle,
     Square,
     Grid,
@@ -12,78 +12,109 @@ use model::{
 };
 
 
+// `Solved`/`Stuck` are internal step() signals — `Solver::solve` converts a
+// `Solved` step into `Ok` with the revealed grid, so callers only ever see
+// `Invalid` (a contradiction) or `Stuck` (propagation and guessing both ran
+// out of options, which a well-formed puzzle should never hit).
 #[derive(PartialEq, Eq, Debug)]
-enum SolverError {
+pub enum SolverError {
     Solved,
     Invalid,
     Stuck,
 }
 
-#[derive(Clone, PartialEq, Eq)]
-enum PartialSquare {
-    Unknown,
-    Known(Square),
-}
+// A cell's remaining candidates: bit 0 is Empty, bit (c + 1) is color `c`.
+// Deductions narrow this down via `reveal` until exactly one bit remains.
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct PartialSquare(u32);
 
 impl PartialSquare {
-    fn collapse(self) -> Square {
-        match self {
-            PartialSquare::Unknown => panic!("Cannot collapse Partial::Unknown"),
-            PartialSquare::Known(inner) => inner,
+    const EMPTY_BIT: u32 = 1;
+
+    fn color_bit(color: ColorId) -> u32 {
+        1 << (color as u32 + 1)
+    }
+
+    fn unknown(num_colors: ColorId) -> Self {
+        let mut bits = PartialSquare::EMPTY_BIT;
+        for color in 0..num_colors {
+            bits |= PartialSquare::color_bit(color);
         }
+        PartialSquare(bits)
     }
 
-    fn is_known(&self) -> bool {
-        match self {
-            PartialSquare::Unknown => false,
-            PartialSquare::Known(_) => true,
+    fn bits(&self) -> u32 {
+        self.0
+    }
+
+    fn candidates(&self) -> Vec<Square> {
+        let mut result = Vec::new();
+
+        if self.0 & PartialSquare::EMPTY_BIT != 0 {
+            result.push(Square::Empty);
+        }
+
+        let mut bits = self.0 >> 1;
+        let mut color = 0;
+        while bits != 0 {
+            if bits & 1 != 0 {
+                result.push(Square::Full(color));
+            }
+            bits >>= 1;
+            color += 1;
         }
+
+        result
+    }
+
+    fn collapse(&self) -> Square {
+        assert!(self.is_known(), "Cannot collapse a PartialSquare with multiple candidates");
+
+        if self.0 == PartialSquare::EMPTY_BIT {
+            Square::Empty
+        } else {
+            Square::Full((self.0.trailing_zeros() - 1) as ColorId)
+        }
+    }
+
+    fn is_known(&self) -> bool {
+        self.0.count_ones() == 1
     }
 
     fn reveal(&mut self, x: Square) -> bool {
-        let res = match *self {
-            PartialSquare::Known(old) if old == x => false,
-            _ => true,
+        let bits = match x {
+            Square::Empty    => PartialSquare::EMPTY_BIT,
+            Square::Full(c)  => PartialSquare::color_bit(c),
         };
 
-        *self = PartialSquare::Known(x);
+        let changed = self.0 != bits;
+        self.0 = bits;
+        changed
+    }
 
-        res
+    // Whether `x` is still among this cell's candidates.
+    fn allows(&self, x: Square) -> bool {
+        let bit = match x {
+            Square::Empty    => PartialSquare::EMPTY_BIT,
+            Square::Full(c)  => PartialSquare::color_bit(c),
+        };
+
+        self.0 & bit != 0
     }
 }
 
 impl fmt::Display for PartialSquare {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match self {
-            PartialSquare::Unknown  => write!(f, " "),
-            PartialSquare::Known(s) => write!(f, "{}", s),
-        }
-    }
-}
-
-#[derive(Clone)]
-struct PartialRun {
-    lo: usize,
-    hi: usize,
-}
-
-impl PartialRun {
-    pub fn new() -> Self {
-        PartialRun {
-            lo: 0,
-            hi: usize::max_value(),
+        if self.is_known() {
+            write!(f, "{}", self.collapse())
+        } else {
+            write!(f, " ")
         }
     }
-
-    fn update(&mut self, lo: usize, hi: usize) {
-        self.lo = max(self.lo, lo);
-        self.hi = min(self.hi, hi);
-    }
 }
 
 struct PartialLine<'a> {
-    hints: &'a [usize],
-    runs: &'a mut [PartialRun],
+    hints: &'a [(usize, ColorId)],
     line: ArrayViewMut1<'a, PartialSquare>,
     dirty: HashSet<usize>,
 }
@@ -94,24 +125,13 @@ impl<'a> PartialLine<'a> {
             self.dirty.insert(i);
         }
     }
+}
 
-    fn reveal_all<I>(&mut self, bag: I, x: Square)
-        where I: IntoIterator<Item = usize> {
-        for i in bag.into_iter() {
-            self.reveal(i, x);
-        }
-    }
-
-    fn reveal_run(&mut self, run_index: usize, lo: usize, hi: usize) {
-        self.reveal_all(lo..hi, Square::Full);
-
-        if hi > self.hints[run_index] {
-            self.runs[run_index].update(hi - self.hints[run_index],
-                                        lo + self.hints[run_index]);
-        } else {
-            self.runs[run_index].update(0,
-                                        lo + self.hints[run_index]);
-        }
+impl Grid<PartialSquare> {
+    // Collapses a fully-known grid into the Squares it reveals; panics if
+    // any cell still has more than one candidate.
+    fn collapse(&self) -> Grid<Square> {
+        Grid(self.0.map(|ps| ps.collapse()))
     }
 }
 
@@ -120,7 +140,6 @@ struct SolverWorker<'a> {
     puzzle: &'a Puzzle,
     grid: Grid<PartialSquare>,
     queue: VecDeque<LineIndex>,
-    runs: HashMap<LineIndex, Vec<PartialRun>>,
 
     // Keep track of other solved features (ex., some tile is part of some run)
     // Deductions should be able to accept extra features
@@ -130,38 +149,48 @@ struct SolverWorker<'a> {
 impl<'a> SolverWorker<'a> {
     fn new(solver: &'a Solver, puzzle: &'a Puzzle) -> SolverWorker<'a> {
         let mut queue = VecDeque::with_capacity(puzzle.w() + puzzle.h());
-        let mut runs = HashMap::with_capacity(puzzle.w() + puzzle.h());
 
         for li in puzzle.index_iter() {
             queue.push_back(li);
-            runs.insert(li, vec![PartialRun::new(); puzzle.hints(li).len()]);
         }
 
+        let unknown = PartialSquare::unknown(puzzle.num_colors());
+
         SolverWorker {
             solver,
             puzzle,
-            grid: Grid(Array2::from_elem((puzzle.h(), puzzle.w()), PartialSquare::Unknown)),
+            grid: Grid(Array2::from_elem((puzzle.h(), puzzle.w()), unknown)),
             queue,
-            runs,
         }
     }
 
     fn line(puzzle: &'a Puzzle,
-            runs: &'a mut HashMap<LineIndex, Vec<PartialRun>>,
             grid: &'a mut Grid<PartialSquare>,
             li: LineIndex) -> PartialLine<'a> {
         PartialLine {
             hints: puzzle.hints(li),
-            runs: runs.get_mut(&li).unwrap(),
             line: grid.line(li),
             dirty: HashSet::new(),
         }
     }
 
     fn verify(&self) -> SolverError {
-        // TODO: Possibly return SolverError::Invalid (that is, perform validation!)
+        for li in self.puzzle.index_iter() {
+            let hints = self.puzzle.hints(li);
+            let line = self.grid.line_view(li);
+
+            let fully_known = line.iter().all(|s| s.is_known());
 
-        if self.grid.0.iter().any(|x| *x == PartialSquare::Unknown) {
+            if fully_known {
+                if run_lengths(&line).as_slice() != hints {
+                    return SolverError::Invalid;
+                }
+            } else if !partial_line_is_consistent(&line, hints) {
+                return SolverError::Invalid;
+            }
+        }
+
+        if self.grid.0.iter().any(|x| !x.is_known()) {
             SolverError::Stuck
         } else {
             SolverError::Solved
@@ -170,7 +199,7 @@ impl<'a> SolverWorker<'a> {
 
     fn step(&mut self) -> Result<(), SolverError> {
         while let Some(li) = self.queue.pop_front() {
-            let mut line = SolverWorker::line(self.puzzle, &mut self.runs, &mut self.grid, li);
+            let mut line = SolverWorker::line(self.puzzle, &mut self.grid, li);
 
             loop {
                 let num_reveals = line.dirty.len();
@@ -196,72 +225,425 @@ impl<'a> SolverWorker<'a> {
         Err(self.verify())
     }
 
-    fn solve(mut self) -> Result<(), SolverError> {
-        loop {
-            println!("{}", self.grid);
+    fn solve(self) -> Result<Grid<Square>, SolverError> {
+        let mut visited = HashSet::new();
+        self.search(&mut visited)
+    }
 
+    // Line propagation alone can get stuck on puzzles that require lookahead
+    // (see `nonlinear_puzzle_smiley`). When that happens, guess a cell and
+    // recurse, backtracking on `Invalid`. `visited` dedupes grid states
+    // reachable through different guess orders.
+    fn search(mut self, visited: &mut HashSet<Vec<u8>>) -> Result<Grid<Square>, SolverError> {
+        loop {
             match self.step() {
-                Err(SolverError::Solved) => return Ok(()),
+                Ok(()) => continue,
+                Err(SolverError::Solved) => return Ok(self.grid.collapse()),
+                Err(SolverError::Invalid) => return Err(SolverError::Invalid),
+                Err(SolverError::Stuck) => break,
+            }
+        }
+
+        if !visited.insert(self.fingerprint()) {
+            return Err(SolverError::Invalid);
+        }
+
+        let (i, j) = self.pick_guess_cell();
+        let ri = LineIndex::Row(i);
+        let cj = LineIndex::Col(j);
+        let candidates = self.grid.0[[i, j]].candidates();
+
+        for guess in candidates {
+            let mut branch = self.clone_for_guess();
+            branch.grid.0[[i, j]].reveal(guess);
+            branch.queue.push_back(ri);
+            branch.queue.push_back(cj);
+
+            match branch.search(visited) {
+                Ok(solution) => return Ok(solution),
+                Err(SolverError::Invalid) => continue,
                 Err(e) => return Err(e),
-                _ => (),
             }
         }
+
+        Err(SolverError::Invalid)
+    }
+
+    fn clone_for_guess(&self) -> SolverWorker<'a> {
+        SolverWorker {
+            solver: self.solver,
+            puzzle: self.puzzle,
+            grid: self.grid.clone(),
+            queue: VecDeque::new(),
+        }
+    }
+
+    // Picks the Unknown cell whose row and column already have the most
+    // known cells, on the theory that it's closest to being pinned down by
+    // propagation after the guess.
+    fn pick_guess_cell(&self) -> (usize, usize) {
+        let row_known: Vec<usize> = (0..self.puzzle.h())
+            .map(|i| self.grid.0.row(i).iter().filter(|s| s.is_known()).count())
+            .collect();
+        let col_known: Vec<usize> = (0..self.puzzle.w())
+            .map(|j| self.grid.0.column(j).iter().filter(|s| s.is_known()).count())
+            .collect();
+
+        let mut best = None;
+        let mut best_score = 0;
+
+        for ((i, j), square) in self.grid.0.indexed_iter() {
+            if !square.is_known() {
+                let score = row_known[i] + col_known[j];
+                if best.is_none() || score > best_score {
+                    best = Some((i, j));
+                    best_score = score;
+                }
+            }
+        }
+
+        best.expect("pick_guess_cell called on a fully-known grid")
+    }
+
+    // Serializes each cell's candidate bitset into bytes, so that equivalent
+    // positions reached via different guess orders can be recognized and
+    // skipped.
+    fn fingerprint(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.grid.0.len() * 4);
+
+        for square in self.grid.0.iter() {
+            bytes.extend_from_slice(&square.bits().to_le_bytes());
+        }
+
+        bytes
+    }
+
+    // Like `search`, but instead of stopping at the first solution it keeps
+    // backtracking to find more, returning the number found. Once `limit`
+    // solutions have been found, the remaining sibling branches are skipped,
+    // so the result is exact below `limit` and otherwise at least `limit`
+    // (it can overshoot `limit`, never the true total). `memo` caches the count already found for a given grid
+    // fingerprint — reachable via more than one guess order — so it's never
+    // recomputed; `limit` stays fixed across the whole recursion (instead of
+    // shrinking by what's already been found) so a cached count remains
+    // valid no matter which sibling guess reaches that fingerprint first.
+    fn count_solutions(mut self, memo: &mut HashMap<Vec<u8>, usize>, limit: usize) -> usize {
+        loop {
+            match self.step() {
+                Ok(()) => continue,
+                Err(SolverError::Solved) => return 1,
+                Err(SolverError::Invalid) => return 0,
+                Err(SolverError::Stuck) => break,
+            }
+        }
+
+        let fingerprint = self.fingerprint();
+        if let Some(&count) = memo.get(&fingerprint) {
+            return count;
+        }
+
+        let (i, j) = self.pick_guess_cell();
+        let ri = LineIndex::Row(i);
+        let cj = LineIndex::Col(j);
+        let candidates = self.grid.0[[i, j]].candidates();
+
+        let mut found = 0;
+        for guess in candidates {
+            if found >= limit {
+                break;
+            }
+
+            let mut branch = self.clone_for_guess();
+            branch.grid.0[[i, j]].reveal(guess);
+            branch.queue.push_back(ri);
+            branch.queue.push_back(cj);
+
+            found += branch.count_solutions(memo, limit);
+        }
+
+        memo.insert(fingerprint, found);
+        found
     }
 }
 
 pub struct Solver {
-    deductions: Vec<Box<Fn(&mut PartialLine)>>,
+    deductions: Vec<Box<dyn Fn(&mut PartialLine)>>,
+}
+
+// How many solutions a puzzle has, capped at the `limit` passed to
+// `Solver::count_solutions` — `Many` only means "at least as many as the
+// search bothered to count", not necessarily the exact total.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SolutionCount {
+    None,
+    Unique,
+    Many,
 }
 
 impl Solver {
     fn new() -> Self {
         Solver {
-            deductions: vec![Box::new(deduce_overlap),
-                             Box::new(deduce_run_gaps)],
+            deductions: vec![Box::new(deduce_line)],
         }
     }
 
     fn delegate<'a>(&'a self, puzzle: &'a Puzzle) -> SolverWorker<'a> {
         SolverWorker::new(self, puzzle)
     }
+
+    // Solves `puzzle` via line propagation plus backtracking, returning the
+    // grid of revealed squares. `Err(SolverError::Invalid)` means the puzzle
+    // has no solution; `Err(SolverError::Stuck)` should never happen for a
+    // well-formed puzzle, since guessing always eventually decides every cell.
+    pub fn solve(puzzle: &Puzzle) -> Result<Grid<Square>, SolverError> {
+        let solver = Solver::new();
+        let worker = solver.delegate(puzzle);
+        worker.solve()
+    }
+
+    // Checks a puzzle's solution count against the uniqueness property a
+    // well-formed nonogram should have. `limit` bounds the search so it
+    // doesn't pay for an exact count on a badly underdetermined puzzle;
+    // callers checking uniqueness can pass 2, since anything beyond that is
+    // already "not unique".
+    pub fn count_solutions(puzzle: &Puzzle, limit: usize) -> SolutionCount {
+        let solver = Solver::new();
+        let worker = solver.delegate(puzzle);
+        let mut memo = HashMap::new();
+
+        match worker.count_solutions(&mut memo, limit) {
+            0 => SolutionCount::None,
+            1 => SolutionCount::Unique,
+            _ => SolutionCount::Many,
+        }
+    }
 }
 
 
-fn deduce_overlap(partial: &mut PartialLine) {
-    let gap_span = if partial.hints.is_empty() { 0 } else { partial.hints.len() - 1 };
-    let span = partial.hints.iter().sum::<usize>() + gap_span;
-    let flexibility = partial.line.len() - span;
+// Run-length encoding of the Full segments of a fully-known line. Adjacent
+// same-color cells merge into one run; adjacent different-color cells (which
+// are allowed to touch) stay as separate runs.
+fn run_lengths(line: &ArrayView1<PartialSquare>) -> Vec<(usize, ColorId)> {
+    let mut runs = Vec::new();
+    let mut current: Option<(usize, ColorId)> = None;
+
+    for square in line.iter() {
+        match square.collapse() {
+            Square::Full(color) => {
+                current = Some(match current {
+                    Some((len, c)) if c == color => (len + 1, c),
+                    Some((len, c)) => { runs.push((len, c)); (1, color) }
+                    None => (1, color),
+                });
+            }
+            Square::Empty => {
+                if let Some(run) = current.take() {
+                    runs.push(run);
+                }
+            }
+        }
+    }
 
-    let mut left = 0;
-    for (i, hint) in partial.hints.iter().enumerate() {
-        let lo = left + flexibility;
-        let hi = left + hint;
+    if let Some(run) = current {
+        runs.push(run);
+    }
+
+    runs
+}
+
+fn is_known_full(square: &PartialSquare) -> Option<ColorId> {
+    match square.is_known() {
+        true => match square.collapse() {
+            Square::Full(color) => Some(color),
+            Square::Empty => None,
+        },
+        false => None,
+    }
+}
 
-        if lo < hi {
-            partial.reveal_run(i, lo, hi);
+fn is_known_empty(square: &PartialSquare) -> bool {
+    square.is_known() && square.collapse() == Square::Empty
+}
+
+// Cheap, partial-information checks for a line that isn't fully known yet:
+// too many committed Full cells already, or a maximal block of Full cells
+// (of any colors) bounded by known Empty/edges on both sides where some
+// same-colored sub-run is longer than any hint of that color can justify.
+fn partial_line_is_consistent(line: &ArrayView1<PartialSquare>, hints: &[(usize, ColorId)]) -> bool {
+    let full_count = line.iter().filter(|s| is_known_full(s).is_some()).count();
+
+    if full_count > hints.iter().map(|&(len, _)| len).sum() {
+        return false;
+    }
+
+    let n = line.len();
+    let mut i = 0;
+
+    while i < n {
+        if let Some(color0) = is_known_full(&line[i]) {
+            let bounded_before = i == 0 || is_known_empty(&line[i - 1]);
+
+            let mut sub_runs = vec![(0usize, color0)];
+            let mut j = i;
+            while let Some(color) = is_known_full(&line[j]) {
+                let last = sub_runs.last_mut().unwrap();
+                if last.1 == color {
+                    last.0 += 1;
+                } else {
+                    sub_runs.push((1, color));
+                }
+                j += 1;
+                if j == n {
+                    break;
+                }
+            }
+
+            let bounded_after = j == n || is_known_empty(&line[j]);
+
+            if bounded_before && bounded_after {
+                for &(len, color) in &sub_runs {
+                    let fits = hints.iter()
+                        .any(|&(hint_len, hint_color)| hint_color == color && hint_len >= len);
+                    if !fits {
+                        return false;
+                    }
+                }
+            }
+
+            i = j;
+        } else {
+            i += 1;
         }
+    }
+
+    true
+}
 
-        left = hi + 1;
+// Forward DP over (cells consumed, runs placed) for a line, given the
+// current candidates of each of its cells. `tight[i][j]` holds when the
+// first `i` cells can be explained by exactly the first `j` hints with run
+// `j` ending exactly at `i` (no slack yet before position `i`); `loose[i][j]`
+// holds when the same is true but at least one Empty cell was consumed right
+// before position `i`, so any color may follow without a forced gap.
+fn line_dp(cells: &[PartialSquare], hints: &[(usize, ColorId)]) -> (Vec<Vec<bool>>, Vec<Vec<bool>>) {
+    let n = cells.len();
+    let k = hints.len();
+
+    let mut tight = vec![vec![false; k + 1]; n + 1];
+    let mut loose = vec![vec![false; k + 1]; n + 1];
+
+    tight[0][0] = true;
+
+    for i in 1..=n {
+        for j in 0..=k {
+            if cells[i - 1].allows(Square::Empty) && (tight[i - 1][j] || loose[i - 1][j]) {
+                loose[i][j] = true;
+            }
+
+            if j == 0 {
+                continue;
+            }
+
+            let (len, color) = hints[j - 1];
+            if i < len || !(i - len..i).all(|c| cells[c].allows(Square::Full(color))) {
+                continue;
+            }
+
+            let prev = i - len;
+            tight[i][j] = if j == 1 {
+                tight[prev][0] || loose[prev][0]
+            } else {
+                let prev_color = hints[j - 2].1;
+                (tight[prev][j - 1] && prev_color != color) || loose[prev][j - 1]
+            };
+        }
     }
+
+    (tight, loose)
 }
 
-fn deduce_run_gaps(partial: &mut PartialLine) {
-    let end = partial.line.len();
+// Whether, given `j` hints already placed, a run can start (with no forced
+// gap) right at position `s` — i.e. either there's already slack before `s`,
+// or the `j`-th run ends exactly at `s` in a color that's free to touch
+// whatever comes next.
+fn can_start(tight: &[Vec<bool>], loose: &[Vec<bool>], hints: &[(usize, ColorId)], j: usize, s: usize) -> bool {
+    if loose[s][j] {
+        return true;
+    }
+    if !tight[s][j] {
+        return false;
+    }
+    j == 0 || j == hints.len() || hints[j - 1].1 != hints[j].1
+}
+
+fn state_reachable(tight: &[Vec<bool>], loose: &[Vec<bool>], j: usize, s: usize) -> bool {
+    tight[s][j] || loose[s][j]
+}
+
+// A complete single-line deduction: run the forward DP above and its mirror
+// image (over the reversed line and hints), then for every cell that every
+// remaining valid arrangement agrees on, reveal it. This subsumes the old
+// `deduce_overlap`/`deduce_run_gaps` pair — unlike plain overlap, it takes
+// already-known cells into account, so it can also force cells that overlap
+// deduction alone would miss.
+fn deduce_line(partial: &mut PartialLine) {
+    let n = partial.line.len();
+    let k = partial.hints.len();
+    let hints = partial.hints;
+
+    let cells: Vec<PartialSquare> = partial.line.iter().cloned().collect();
+    let hints_rev: Vec<(usize, ColorId)> = hints.iter().cloned().rev().collect();
+    let cells_rev: Vec<PartialSquare> = cells.iter().cloned().rev().collect();
+
+    let (tight, loose) = line_dp(&cells, hints);
+    let (tight_b, loose_b) = line_dp(&cells_rev, &hints_rev);
+
+    if !can_start(&tight, &loose, hints, k, n) {
+        // No arrangement is reachable from what's currently known; leave the
+        // contradiction for `verify`/backtracking to catch.
+        return;
+    }
+
+    // Colors that can cover each cell in some still-reachable arrangement.
+    let mut covers: Vec<Vec<ColorId>> = vec![Vec::new(); n];
 
-    if partial.runs.is_empty() {
-        partial.reveal_all(0..end, Square::Empty);
-    } else {
-        let lo = partial.runs.first().unwrap().lo;
-        let hi = partial.runs.last().unwrap().hi;
+    for r in 1..=k {
+        let (len, color) = hints[r - 1];
+        if len > n {
+            continue;
+        }
+
+        for s in 0..=(n - len) {
+            if !(s..s + len).all(|i| cells[i].allows(Square::Full(color))) {
+                continue;
+            }
+            if !can_start(&tight, &loose, hints, r - 1, s) {
+                continue;
+            }
+
+            let s_rev = n - (s + len);
+            if !can_start(&tight_b, &loose_b, &hints_rev, k - r, s_rev) {
+                continue;
+            }
 
-        partial.reveal_all(0..lo, Square::Empty);
-        partial.reveal_all(hi..end, Square::Empty);
+            for i in s..s + len {
+                if !covers[i].contains(&color) {
+                    covers[i].push(color);
+                }
+            }
+        }
+    }
 
-        for i in 0..partial.runs.len() - 1 {
-            let lo = partial.runs[i].hi;
-            let hi = partial.runs[i + 1].lo;
+    for p in 0..n {
+        let can_be_empty = cells[p].allows(Square::Empty) && (0..=k).any(|j| {
+            state_reachable(&tight, &loose, j, p)
+                && state_reachable(&tight_b, &loose_b, k - j, n - p - 1)
+        });
 
-            partial.reveal_all(lo..hi, Square::Empty);
+        if !can_be_empty && covers[p].len() == 1 {
+            partial.reveal(p, Square::Full(covers[p][0]));
+        } else if can_be_empty && covers[p].is_empty() {
+            partial.reveal(p, Square::Empty);
         }
     }
 }
@@ -270,20 +652,26 @@ fn deduce_run_gaps(partial: &mut PartialLine) {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use ndarray::Array1;
+
+    // Monochrome hint lines still use plain run lengths in color 0.
+    fn mono(lens: Vec<usize>) -> Vec<(usize, ColorId)> {
+        lens.into_iter().map(|len| (len, 0)).collect()
+    }
 
     #[test]
     fn easy_puzzle_snake() {
         let puzzle = Puzzle::new()
-            .push_row(vec!(5))
-            .push_row(vec!(1))
-            .push_row(vec!(5))
-            .push_row(vec!(1))
-            .push_row(vec!(5))
-            .push_col(vec!(3, 1))
-            .push_col(vec!(1, 1, 1))
-            .push_col(vec!(1, 1, 1))
-            .push_col(vec!(1, 1, 1))
-            .push_col(vec!(1, 3));
+            .push_row(mono(vec!(5)))
+            .push_row(mono(vec!(1)))
+            .push_row(mono(vec!(5)))
+            .push_row(mono(vec!(1)))
+            .push_row(mono(vec!(5)))
+            .push_col(mono(vec!(3, 1)))
+            .push_col(mono(vec!(1, 1, 1)))
+            .push_col(mono(vec!(1, 1, 1)))
+            .push_col(mono(vec!(1, 1, 1)))
+            .push_col(mono(vec!(1, 3)));
 
         let solver = Solver::new();
         let worker = solver.delegate(&puzzle);
@@ -294,16 +682,16 @@ mod tests {
     #[test]
     fn easy_puzzle_checkerboard() {
         let puzzle = Puzzle::new()
-            .push_row(vec!(1, 1, 1))
-            .push_row(vec!(1, 1))
-            .push_row(vec!(1, 1, 1))
-            .push_row(vec!(1, 1))
-            .push_row(vec!(1, 1, 1))
-            .push_col(vec!(1, 1, 1))
-            .push_col(vec!(1, 1))
-            .push_col(vec!(1, 1, 1))
-            .push_col(vec!(1, 1))
-            .push_col(vec!(1, 1, 1));
+            .push_row(mono(vec!(1, 1, 1)))
+            .push_row(mono(vec!(1, 1)))
+            .push_row(mono(vec!(1, 1, 1)))
+            .push_row(mono(vec!(1, 1)))
+            .push_row(mono(vec!(1, 1, 1)))
+            .push_col(mono(vec!(1, 1, 1)))
+            .push_col(mono(vec!(1, 1)))
+            .push_col(mono(vec!(1, 1, 1)))
+            .push_col(mono(vec!(1, 1)))
+            .push_col(mono(vec!(1, 1, 1)));
 
         let solver = Solver::new();
         let worker = solver.delegate(&puzzle);
@@ -314,16 +702,16 @@ mod tests {
     #[test]
     fn easy_puzzle_stairs() {
         let puzzle = Puzzle::new()
-            .push_row(vec!(2))
-            .push_row(vec!(3))
-            .push_row(vec!(2, 1))
-            .push_row(vec!(2, 1))
-            .push_row(vec!(5))
-            .push_col(vec!(2))
-            .push_col(vec!(3))
-            .push_col(vec!(2, 1))
-            .push_col(vec!(2, 1))
-            .push_col(vec!(5));
+            .push_row(mono(vec!(2)))
+            .push_row(mono(vec!(3)))
+            .push_row(mono(vec!(2, 1)))
+            .push_row(mono(vec!(2, 1)))
+            .push_row(mono(vec!(5)))
+            .push_col(mono(vec!(2)))
+            .push_col(mono(vec!(3)))
+            .push_col(mono(vec!(2, 1)))
+            .push_col(mono(vec!(2, 1)))
+            .push_col(mono(vec!(5)));
 
         let solver = Solver::new();
         let worker = solver.delegate(&puzzle);
@@ -334,21 +722,161 @@ mod tests {
     #[test]
     fn nonlinear_puzzle_smiley() {
         let puzzle = Puzzle::new()
-            .push_row(vec!(2, 2))
-            .push_row(vec!(2, 2))
-            .push_row(vec!())
-            .push_row(vec!(1, 1))
-            .push_row(vec!(3))
-            .push_col(vec!(2, 1))
-            .push_col(vec!(2, 1))
-            .push_col(vec!(1))
-            .push_col(vec!(2, 1))
-            .push_col(vec!(2, 1));
+            .push_row(mono(vec!(2, 2)))
+            .push_row(mono(vec!(2, 2)))
+            .push_row(mono(vec!()))
+            .push_row(mono(vec!(1, 1)))
+            .push_row(mono(vec!(3)))
+            .push_col(mono(vec!(2, 1)))
+            .push_col(mono(vec!(2, 1)))
+            .push_col(mono(vec!(1)))
+            .push_col(mono(vec!(2, 1)))
+            .push_col(mono(vec!(2, 1)));
+
+        let solver = Solver::new();
+        let worker = solver.delegate(&puzzle);
+
+        // Line propagation alone gets stuck here, but the backtracking
+        // search in `solve` can still crack it by guessing.
+        assert!(worker.solve().is_ok());
+    }
+
+    #[test]
+    fn colored_puzzle_two_color_stripes() {
+        // A 1x2 grid: left cell color 0, right cell color 1, touching with
+        // no forced gap since they're different colors.
+        let puzzle = Puzzle::new()
+            .push_row(vec!((1, 0), (1, 1)))
+            .push_col(vec!((1, 0)))
+            .push_col(vec!((1, 1)));
 
         let solver = Solver::new();
         let worker = solver.delegate(&puzzle);
 
-        assert_eq!(worker.solve(), Err(SolverError::Stuck));
+        assert!(worker.solve().is_ok());
+    }
+
+    #[test]
+    fn complete_line_solver_uses_already_known_cells() {
+        // Hints (2, 2) on a line of length 6: with no other information the
+        // only forced cells are index 1 and index 4 (each run has two valid
+        // starts, and both cover that cell). But if index 3 is already known
+        // Empty, the second run
+        // can no longer start at 3, pinning it to start at 4 and forcing
+        // index 5 too — something plain overlap, which ignores already-known
+        // cells, could never deduce.
+        let hints = mono(vec!(2, 2));
+        let mut cells = Array1::from_elem(6, PartialSquare::unknown(1));
+        cells[3].reveal(Square::Empty);
+
+        let mut line = PartialLine {
+            hints: &hints,
+            line: cells.view_mut(),
+            dirty: HashSet::new(),
+        };
+
+        deduce_line(&mut line);
+
+        assert!(line.line[1].collapse() == Square::Full(0));
+        assert!(line.line[5].collapse() == Square::Full(0));
+    }
+
+    #[test]
+    fn complete_line_solver_forces_last_cell_after_leading_empty() {
+        // Hint (1) on a line of length 2 with cell0 known Empty: the only
+        // valid arrangement is "x0", so cell1 must be forced Full. The DP's
+        // first-run transition has to recognize `loose` (not just `tight`)
+        // arriving at cell0, or it wrongly treats the run as unreachable.
+        let hints = mono(vec!(1));
+        let mut cells = Array1::from_elem(2, PartialSquare::unknown(1));
+        cells[0].reveal(Square::Empty);
+
+        let mut line = PartialLine {
+            hints: &hints,
+            line: cells.view_mut(),
+            dirty: HashSet::new(),
+        };
+
+        deduce_line(&mut line);
+
+        assert!(line.line[1].collapse() == Square::Full(0));
+    }
+
+    #[test]
+    fn complete_line_solver_leaves_genuinely_ambiguous_cell_unforced() {
+        // Hint (1) on a line of length 3 with cell2 known Empty: both "0xx"
+        // and "x0x" are valid, so cell0 is genuinely ambiguous and must not
+        // be force-revealed.
+        let hints = mono(vec!(1));
+        let mut cells = Array1::from_elem(3, PartialSquare::unknown(1));
+        cells[2].reveal(Square::Empty);
+
+        let mut line = PartialLine {
+            hints: &hints,
+            line: cells.view_mut(),
+            dirty: HashSet::new(),
+        };
+
+        deduce_line(&mut line);
+
+        assert!(!line.line[0].is_known());
+    }
+
+    #[test]
+    fn solve_reports_invalid_for_a_contradictory_puzzle() {
+        // A 1x1 grid whose row hint demands a filled cell but whose col hint
+        // demands an empty one: line propagation alone pins the cell down
+        // (no guessing needed), and `verify` must catch the clash.
+        let puzzle = Puzzle::new()
+            .push_row(mono(vec!(1)))
+            .push_col(mono(vec!()));
+
+        let solver = Solver::new();
+        let worker = solver.delegate(&puzzle);
+
+        assert_eq!(worker.solve(), Err(SolverError::Invalid));
+    }
+
+    #[test]
+    fn solver_solve_exposes_the_solved_grid() {
+        let puzzle = Puzzle::new()
+            .push_row(mono(vec!(1)))
+            .push_col(mono(vec!(1)));
+
+        let grid = Solver::solve(&puzzle).unwrap();
+
+        assert_eq!(grid.0[[0, 0]], Square::Full(0));
+    }
+
+    #[test]
+    fn count_solutions_reports_none_for_a_contradictory_puzzle() {
+        let puzzle = Puzzle::new()
+            .push_row(mono(vec!(1)))
+            .push_col(mono(vec!()));
+
+        assert_eq!(Solver::count_solutions(&puzzle, 2), SolutionCount::None);
+    }
+
+    #[test]
+    fn count_solutions_reports_unique_for_a_determined_puzzle() {
+        let puzzle = Puzzle::new()
+            .push_row(mono(vec!(1)))
+            .push_col(mono(vec!(1)));
+
+        assert_eq!(Solver::count_solutions(&puzzle, 2), SolutionCount::Unique);
+    }
+
+    #[test]
+    fn count_solutions_reports_many_for_an_ambiguous_puzzle() {
+        // A 2x2 grid with one cell filled per row and per column has two
+        // solutions: either diagonal.
+        let puzzle = Puzzle::new()
+            .push_row(mono(vec!(1)))
+            .push_row(mono(vec!(1)))
+            .push_col(mono(vec!(1)))
+            .push_col(mono(vec!(1)));
+
+        assert_eq!(Solver::count_solutions(&puzzle, 2), SolutionCount::Many);
     }
 }
 